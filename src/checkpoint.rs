@@ -0,0 +1,119 @@
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{DependentKind, ResolveError};
+
+/// One newline-delimited entry in the checkpoint file: either a dedup key
+/// marking a crate@version as processed, or one of the two results recorded
+/// for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum CheckpointLine {
+    Processed(String),
+    Dependent(String, semver::Version, DependentKind),
+    ResolveError(ResolveError),
+}
+
+/// Everything reloaded from an existing checkpoint file on startup.
+#[derive(Debug, Default)]
+pub struct CheckpointState {
+    pub processed: HashSet<String>,
+    pub dependents: Vec<(String, semver::Version, DependentKind)>,
+    pub resolve_errors: Vec<ResolveError>,
+}
+
+/// Append-only log of already-resolved crates, so an interrupted run of the
+/// resolve loop can be resumed without redoing work. Workers append through
+/// a `parking_lot::Mutex`-guarded buffered writer so concurrent appends from
+/// the `par_bridge` resolve loop stay consistent.
+pub struct Checkpoint {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Checkpoint {
+    /// Loads any existing checkpoint at `path` (if present) and opens it for
+    /// appending further entries.
+    pub fn open(path: &Path) -> anyhow::Result<(Self, CheckpointState)> {
+        let mut state = CheckpointState::default();
+
+        if let Ok(file) = File::open(path) {
+            for line in BufReader::new(file).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        eprintln!(
+                            "warning: checkpoint {} has an unreadable line, skipping: {err}",
+                            path.display()
+                        );
+                        continue;
+                    }
+                };
+
+                // A process killed mid-write can leave a truncated trailing
+                // line; skip it rather than failing the whole load, since
+                // surviving that is the entire point of checkpointing.
+                let parsed = match serde_json::from_str(&line) {
+                    Ok(parsed) => parsed,
+                    Err(err) => {
+                        eprintln!(
+                            "warning: checkpoint {} has a malformed line, skipping: {err}",
+                            path.display()
+                        );
+                        continue;
+                    }
+                };
+
+                match parsed {
+                    CheckpointLine::Processed(key) => {
+                        state.processed.insert(key);
+                    }
+                    CheckpointLine::Dependent(crate_name, version, kind) => {
+                        state.dependents.push((crate_name, version, kind));
+                    }
+                    CheckpointLine::ResolveError(err) => state.resolve_errors.push(err),
+                }
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let checkpoint = Checkpoint {
+            writer: Mutex::new(BufWriter::new(file)),
+        };
+        Ok((checkpoint, state))
+    }
+
+    fn append(&self, line: &CheckpointLine) -> anyhow::Result<()> {
+        let mut writer = self.writer.lock();
+        serde_json::to_writer(&mut *writer, line)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn record_processed(&self, key: &str) -> anyhow::Result<()> {
+        self.append(&CheckpointLine::Processed(key.to_string()))
+    }
+
+    pub fn record_dependent(
+        &self,
+        crate_name: &str,
+        version: &semver::Version,
+        kind: &DependentKind,
+    ) -> anyhow::Result<()> {
+        self.append(&CheckpointLine::Dependent(
+            crate_name.to_string(),
+            version.clone(),
+            kind.clone(),
+        ))
+    }
+
+    pub fn record_resolve_error(&self, err: &ResolveError) -> anyhow::Result<()> {
+        self.append(&CheckpointLine::ResolveError(err.clone()))
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crates_index::Version;
+
+/// One value out of a feature's dependency list, per cargo's modern feature
+/// grammar: a plain feature name, `dep:foo` (activates an optional
+/// dependency without an implicit feature of the same name), `foo/bar`
+/// (activates dependency `foo` and its feature `bar`), or weak `foo?/bar`
+/// (activates `bar` only if `foo` is otherwise enabled).
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FeatureValue {
+    Feature(String),
+    Dep(String),
+    DepFeature {
+        dep: String,
+        feature: String,
+        weak: bool,
+    },
+}
+
+impl FeatureValue {
+    fn parse(raw: &str) -> Self {
+        if let Some(dep) = raw.strip_prefix("dep:") {
+            return FeatureValue::Dep(dep.to_string());
+        }
+
+        if let Some((dep, feature)) = raw.split_once('/') {
+            return match dep.strip_suffix('?') {
+                Some(dep) => FeatureValue::DepFeature {
+                    dep: dep.to_string(),
+                    feature: feature.to_string(),
+                    weak: true,
+                },
+                None => FeatureValue::DepFeature {
+                    dep: dep.to_string(),
+                    feature: feature.to_string(),
+                    weak: false,
+                },
+            };
+        }
+
+        FeatureValue::Feature(raw.to_string())
+    }
+}
+
+/// The result of transitively activating a requested feature set: every
+/// feature that ended up on, every optional dependency that got pulled in,
+/// and the sub-features requested of each of those dependencies via
+/// `foo/bar`-style syntax.
+#[derive(Debug, Clone, Default)]
+pub struct ActivatedFeatures {
+    pub features: HashSet<String>,
+    pub optional_dependencies: HashSet<String>,
+    pub dependency_features: HashMap<String, HashSet<String>>,
+}
+
+/// Computes which features and optional dependencies of `version` actually
+/// become active given `requested` plus `default_features`, via a
+/// worklist/fixpoint traversal over the raw feature table and the optional
+/// dependency list, the way cargo resolves features once a package has been
+/// selected. A weak `foo?/bar` reference is only resolved once the whole
+/// worklist has drained, so it doesn't matter whether `dep:foo`/`foo` is
+/// activated before or after the `foo?/bar` entry that depends on it.
+/// Detects cycles in the feature graph and reports references to unknown
+/// features.
+pub fn resolve_features(
+    version: &Version,
+    requested: &[String],
+    default_features: bool,
+) -> anyhow::Result<ActivatedFeatures> {
+    let feature_table: HashMap<String, Vec<String>> = version
+        .features()
+        .iter()
+        .map(|(name, values)| (name.clone(), values.clone()))
+        .collect();
+    let optional_dependencies: HashSet<String> = version
+        .dependencies()
+        .iter()
+        .filter(|dependency| dependency.is_optional())
+        .map(|dependency| dependency.name().to_string())
+        .collect();
+    // Non-optional dependencies are always present in the build, so a weak
+    // `dep?/feature` reference to one of them is unconditionally active, the
+    // same as a strong `dep/feature` reference would be.
+    let required_dependencies: HashSet<String> = version
+        .dependencies()
+        .iter()
+        .filter(|dependency| !dependency.is_optional())
+        .map(|dependency| dependency.name().to_string())
+        .collect();
+
+    let mut roots: Vec<String> = requested.to_vec();
+    if default_features && feature_table.contains_key("default") {
+        roots.push("default".to_string());
+    }
+    check_feature_cycles(&roots, &feature_table)?;
+
+    let mut activated = ActivatedFeatures::default();
+    let mut pending_features: VecDeque<String> = VecDeque::new();
+    let mut pending_weak: Vec<(String, String)> = Vec::new();
+
+    for raw in &roots {
+        queue_value(
+            raw,
+            &optional_dependencies,
+            &mut activated,
+            &mut pending_features,
+            &mut pending_weak,
+        );
+    }
+
+    while let Some(name) = pending_features.pop_front() {
+        if !activated.features.insert(name.clone()) {
+            continue;
+        }
+
+        if optional_dependencies.contains(&name) {
+            activated.optional_dependencies.insert(name.clone());
+        }
+
+        match feature_table.get(&name) {
+            Some(values) => {
+                for value in values {
+                    queue_value(
+                        value,
+                        &optional_dependencies,
+                        &mut activated,
+                        &mut pending_features,
+                        &mut pending_weak,
+                    );
+                }
+            }
+            None if optional_dependencies.contains(&name) => {}
+            None => anyhow::bail!("unknown feature reference: {name}"),
+        }
+    }
+
+    // Every optional dependency that's ever going to be activated already
+    // is by now, so weak refs can be resolved in a single trailing pass
+    // regardless of the order they were encountered in. A weak ref to a
+    // non-optional dependency is always active, since that dependency is
+    // always present in the build.
+    for (dep, feature) in pending_weak {
+        if activated.optional_dependencies.contains(&dep) || required_dependencies.contains(&dep)
+        {
+            activated
+                .dependency_features
+                .entry(dep)
+                .or_default()
+                .insert(feature);
+        }
+    }
+
+    Ok(activated)
+}
+
+fn queue_value(
+    raw: &str,
+    optional_dependencies: &HashSet<String>,
+    activated: &mut ActivatedFeatures,
+    pending_features: &mut VecDeque<String>,
+    pending_weak: &mut Vec<(String, String)>,
+) {
+    match FeatureValue::parse(raw) {
+        FeatureValue::Feature(name) => pending_features.push_back(name),
+        FeatureValue::Dep(dep) => {
+            activated.optional_dependencies.insert(dep);
+        }
+        FeatureValue::DepFeature { dep, feature, weak } => {
+            if weak {
+                pending_weak.push((dep, feature));
+            } else {
+                if optional_dependencies.contains(&dep) {
+                    activated.optional_dependencies.insert(dep.clone());
+                }
+                activated
+                    .dependency_features
+                    .entry(dep)
+                    .or_default()
+                    .insert(feature);
+            }
+        }
+    }
+}
+
+/// Walks the plain-`Feature` edges of the feature graph reachable from
+/// `roots` and bails as soon as a path revisits a feature that is still on
+/// the current path. Run as a separate pre-pass (rather than folded into
+/// the worklist above) so memoizing "already activated" there doesn't also
+/// mask "still being visited", which is what let a cycle slip through
+/// undetected before.
+fn check_feature_cycles(
+    roots: &[String],
+    feature_table: &HashMap<String, Vec<String>>,
+) -> anyhow::Result<()> {
+    let mut visiting = HashSet::new();
+    let mut done = HashSet::new();
+
+    for root in roots {
+        if let FeatureValue::Feature(name) = FeatureValue::parse(root) {
+            visit_for_cycles(&name, feature_table, &mut visiting, &mut done)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn visit_for_cycles(
+    name: &str,
+    feature_table: &HashMap<String, Vec<String>>,
+    visiting: &mut HashSet<String>,
+    done: &mut HashSet<String>,
+) -> anyhow::Result<()> {
+    if done.contains(name) {
+        return Ok(());
+    }
+
+    if !visiting.insert(name.to_string()) {
+        anyhow::bail!("cyclic feature reference: {name}");
+    }
+
+    if let Some(values) = feature_table.get(name) {
+        for value in values {
+            if let FeatureValue::Feature(next) = FeatureValue::parse(value) {
+                visit_for_cycles(&next, feature_table, visiting, done)?;
+            }
+        }
+    }
+
+    visiting.remove(name);
+    done.insert(name.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a real `crates_index::Version` the same way [`crate::index`]
+    /// does, by deserializing the crates.io index's own JSON line format,
+    /// so these tests exercise [`resolve_features`] exactly as it runs
+    /// against real index data rather than a hand-rolled stand-in.
+    fn version(
+        features: &[(&str, &[&str])],
+        optional_deps: &[&str],
+        required_deps: &[&str],
+    ) -> Version {
+        let features: serde_json::Map<String, serde_json::Value> = features
+            .iter()
+            .map(|(name, values)| ((*name).into(), (*values).into()))
+            .collect();
+
+        let deps: Vec<serde_json::Value> = optional_deps
+            .iter()
+            .map(|dep| (dep, true))
+            .chain(required_deps.iter().map(|dep| (dep, false)))
+            .map(|(dep, optional)| {
+                serde_json::json!({
+                    "name": dep,
+                    "req": "*",
+                    "features": [],
+                    "optional": optional,
+                    "default_features": true,
+                    "target": null,
+                    "kind": "normal",
+                    "registry": null,
+                    "package": null,
+                })
+            })
+            .collect();
+
+        serde_json::from_value(serde_json::json!({
+            "name": "subject",
+            "vers": "1.0.0",
+            "deps": deps,
+            "cksum": "0".repeat(64),
+            "features": features,
+            "yanked": false,
+        }))
+        .expect("valid index version fixture")
+    }
+
+    #[test]
+    fn weak_dep_feature_activates_regardless_of_requested_order() {
+        let version = version(&[("a", &["b?/x", "dep:b"])], &["b"], &[]);
+        let activated = resolve_features(&version, &["a".to_string()], false).unwrap();
+
+        assert!(activated.optional_dependencies.contains("b"));
+        assert_eq!(
+            activated.dependency_features.get("b"),
+            Some(&HashSet::from(["x".to_string()]))
+        );
+    }
+
+    #[test]
+    fn weak_dep_feature_stays_off_when_dep_never_activates() {
+        let version = version(&[("a", &["b?/x"])], &["b"], &[]);
+        let activated = resolve_features(&version, &["a".to_string()], false).unwrap();
+
+        assert!(!activated.optional_dependencies.contains("b"));
+        assert!(activated.dependency_features.get("b").is_none());
+    }
+
+    #[test]
+    fn weak_dep_feature_activates_for_a_non_optional_dependency() {
+        let version = version(&[("a", &["b?/x"])], &[], &["b"]);
+        let activated = resolve_features(&version, &["a".to_string()], false).unwrap();
+
+        assert_eq!(
+            activated.dependency_features.get("b"),
+            Some(&HashSet::from(["x".to_string()]))
+        );
+    }
+
+    #[test]
+    fn strong_dep_feature_activates_the_dependency() {
+        let version = version(&[("a", &["b/x"])], &["b"], &[]);
+        let activated = resolve_features(&version, &["a".to_string()], false).unwrap();
+
+        assert!(activated.optional_dependencies.contains("b"));
+        assert_eq!(
+            activated.dependency_features.get("b"),
+            Some(&HashSet::from(["x".to_string()]))
+        );
+    }
+
+    #[test]
+    fn cyclic_feature_reference_is_rejected() {
+        let version = version(&[("a", &["b"]), ("b", &["a"])], &[], &[]);
+        let err = resolve_features(&version, &["a".to_string()], false).unwrap_err();
+        assert!(err.to_string().contains("cyclic feature reference"));
+    }
+
+    #[test]
+    fn unreferenced_cycle_does_not_error() {
+        let version = version(&[("a", &[]), ("b", &["c"]), ("c", &["b"])], &[], &[]);
+        resolve_features(&version, &["a".to_string()], false).unwrap();
+    }
+
+    #[test]
+    fn unknown_feature_reference_is_rejected() {
+        let version = version(&[("a", &["missing"])], &[], &[]);
+        let err = resolve_features(&version, &["a".to_string()], false).unwrap_err();
+        assert!(err.to_string().contains("unknown feature reference"));
+    }
+
+    #[test]
+    fn default_feature_pulled_in_when_enabled() {
+        let version = version(&[("default", &["a"]), ("a", &[])], &[], &[]);
+        let activated = resolve_features(&version, &[], true).unwrap();
+        assert!(activated.features.contains("a"));
+    }
+}
@@ -1,32 +1,101 @@
-use std::{collections::{BTreeMap, HashMap}, num::NonZeroU32, path::Path, sync::atomic::AtomicBool};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    num::NonZeroU32,
+    path::Path,
+    sync::atomic::AtomicBool,
+};
 
 use anyhow::Context;
 use dashmap::mapref::entry;
+use fixedbitset::FixedBitSet;
 use gix::{
-    Progress, Repository,
-    progress::Discard,
+    Repository,
     remote::{Direction, fetch::Shallow},
 };
 use ignore::{DirEntry, WalkBuilder};
 use rayon::iter::{ParallelBridge, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use string_interner::{DefaultSymbol, StringInterner, Symbol};
 
-use crate::INDEX_PATH;
+use crate::{INDEX_PATH, progress::Progress};
 
-pub fn ensure_index() -> anyhow::Result<()> {
+/// Ensures the local crates.io index snapshot exists, cloning it if missing.
+/// If it already exists and `refresh` is set, fetches the latest state from
+/// the remote and fast-forwards the checked-out worktree instead of reusing
+/// the stale snapshot as-is.
+pub fn ensure_index(progress: &mut Progress, refresh: bool) -> anyhow::Result<()> {
     let url = crates_index::git::URL;
-
     let path = INDEX_PATH.as_path();
-    if gix::open(path).is_ok() {
-        return Ok(());
-    };
+
+    match gix::open(path) {
+        Ok(repo) if refresh => return refresh_index(repo, progress),
+        Ok(_) => return Ok(()),
+        Err(_) => {}
+    }
 
     let prepare_clone = gix::prepare_clone(url, path)?;
     let (mut prepare_checkout, _) = prepare_clone
         .with_shallow(Shallow::DepthAtRemote(
             const { NonZeroU32::new(1).unwrap() },
         ))
-        .fetch_then_checkout(Discard, &AtomicBool::new(false))?;
-    prepare_checkout.main_worktree(Discard, &AtomicBool::new(false))?;
+        .fetch_then_checkout(progress.gix("Cloning", "crates.io registry"), &AtomicBool::new(false))?;
+    prepare_checkout.main_worktree(
+        progress.gix("Checking out", "crates.io registry"),
+        &AtomicBool::new(false),
+    )?;
+
+    Ok(())
+}
+
+/// Performs a shallow fetch against the existing index repository's remote
+/// and fast-forwards the main worktree to the newly fetched `HEAD` tree.
+fn refresh_index(repo: Repository, progress: &mut Progress) -> anyhow::Result<()> {
+    let remote = repo
+        .find_default_remote(Direction::Fetch)
+        .context("index repository has no configured remote")??;
+
+    let outcome = remote
+        .connect(Direction::Fetch)?
+        .prepare_fetch(progress.gix("Fetching", "crates.io registry"), Default::default())?
+        .with_shallow(Shallow::DepthAtRemote(
+            const { NonZeroU32::new(1).unwrap() },
+        ))
+        .receive(&AtomicBool::new(false))?;
+
+    let Some(new_head) = outcome
+        .ref_map
+        .mappings
+        .first()
+        .and_then(|mapping| mapping.remote.as_id())
+    else {
+        return Ok(());
+    };
+
+    let tree_id = repo.find_object(new_head)?.peel_to_tree()?.id();
+    gix::worktree::state::checkout(
+        &repo,
+        tree_id,
+        repo.index_or_empty()?.as_ref().clone(),
+        &Default::default(),
+        &AtomicBool::new(false),
+        progress.gix("Checking out", "crates.io registry"),
+    )?;
+
+    // The checkout above only updates the worktree; without this, HEAD (and
+    // whatever branch it points at) keeps referring to the pre-fetch commit,
+    // out of step with the worktree that was just checked out.
+    repo.edit_reference(gix::refs::transaction::RefEdit {
+        change: gix::refs::transaction::Change::Update {
+            log: gix::refs::transaction::LogChange {
+                message: "fast-forward after index refresh".into(),
+                ..Default::default()
+            },
+            expected: gix::refs::transaction::PreviousValue::Any,
+            new: gix::refs::Target::Object(new_head),
+        },
+        name: "HEAD".try_into()?,
+        deref: true,
+    })?;
 
     Ok(())
 }
@@ -73,3 +142,133 @@ pub fn parse_index(
         })
         .collect()
 }
+
+/// Interned crate name, used as the key of the dependency graph so that
+/// ~400k crate names don't have to be stored (and hashed) as owned strings.
+type Sym = DefaultSymbol;
+
+/// Direct reverse-dependent counts for a single crate, split by whether the
+/// dependency is unconditionally required or only pulled in behind an
+/// optional/feature-gated dependency.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RevDepCount {
+    pub required: u32,
+    pub optional: u32,
+}
+
+/// Direct and transitive reverse-dependent counts for a single crate.
+/// `transitive` is only populated for crates named in `build_rev_dep_graph`'s
+/// `transitive_targets`: a full BFS per crate would be O(N·E) over the whole
+/// index, so it's restricted to the handful of crates a caller actually
+/// wants a transitive count for.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RevDependencies {
+    pub direct: RevDepCount,
+    pub transitive: Option<u32>,
+}
+
+/// Builds the reverse-dependency graph straight from the index metadata,
+/// without running `cargo`'s resolver at all. For each crate we take its
+/// latest non-yanked version, read its already-parsed dependency list, and
+/// invert the resulting forward map into `dep_name -> Vec<dependent>`.
+///
+/// This is orders of magnitude cheaper than resolving each crate with
+/// `cargo::ops::resolve_with_previous`, at the cost of not accounting for
+/// version requirements or feature unification. Transitive reverse-dependent
+/// counts (a BFS per crate) are only computed for `transitive_targets`,
+/// rather than for every crate in the index.
+pub fn build_rev_dep_graph(
+    index: &HashMap<String, BTreeMap<semver::Version, crates_index::Version>>,
+    transitive_targets: &HashSet<&str>,
+) -> BTreeMap<String, RevDependencies> {
+    let mut interner = StringInterner::<string_interner::DefaultBackend>::default();
+    let mut forward: HashMap<Sym, Vec<(Sym, bool)>> = HashMap::with_capacity(index.len());
+
+    for (name, versions) in index {
+        let Some((_, version)) = versions.iter().rev().find(|(_, version)| !version.is_yanked())
+        else {
+            continue;
+        };
+
+        let sym = interner.get_or_intern(name);
+        let deps = version
+            .dependencies()
+            .iter()
+            .map(|dependency| {
+                (
+                    interner.get_or_intern(dependency.crate_name()),
+                    dependency.is_optional(),
+                )
+            })
+            .collect();
+        forward.insert(sym, deps);
+    }
+
+    let mut reverse: HashMap<Sym, Vec<(Sym, bool)>> = HashMap::new();
+    for (&dependent, deps) in &forward {
+        for &(dep_name, optional) in deps {
+            reverse.entry(dep_name).or_default().push((dependent, optional));
+        }
+    }
+
+    forward
+        .keys()
+        .map(|&sym| {
+            let name = interner
+                .resolve(sym)
+                .expect("every forward key was interned above")
+                .to_string();
+
+            let direct = reverse
+                .get(&sym)
+                .map(|dependents| {
+                    dependents
+                        .iter()
+                        .fold(RevDepCount::default(), |mut count, &(_, optional)| {
+                            if optional {
+                                count.optional += 1;
+                            } else {
+                                count.required += 1;
+                            }
+                            count
+                        })
+                })
+                .unwrap_or_default();
+            let transitive = transitive_targets
+                .contains(name.as_str())
+                .then(|| count_transitive_rev_deps(sym, &reverse, interner.len()));
+
+            (name, RevDependencies { direct, transitive })
+        })
+        .collect()
+}
+
+/// BFS over the inverted dependency graph, counting distinct transitive
+/// reverse dependents of `start`. Visited nodes are tracked in a bitset over
+/// interned symbols rather than a `HashSet` to keep this cheap at index scale.
+fn count_transitive_rev_deps(
+    start: Sym,
+    reverse: &HashMap<Sym, Vec<(Sym, bool)>>,
+    symbol_count: usize,
+) -> u32 {
+    let mut visited = FixedBitSet::with_capacity(symbol_count);
+    let mut queue = VecDeque::from([start]);
+    visited.insert(start.to_usize());
+
+    let mut count = 0u32;
+    while let Some(current) = queue.pop_front() {
+        let Some(dependents) = reverse.get(&current) else {
+            continue;
+        };
+        for &(dependent, _) in dependents {
+            let idx = dependent.to_usize();
+            if visited.contains(idx) {
+                continue;
+            }
+            visited.insert(idx);
+            count += 1;
+            queue.push_back(dependent);
+        }
+    }
+    count
+}
@@ -1,13 +1,60 @@
+use cargo_util_schemas::manifest::RustVersion;
 use crates_index::{Crate, Version};
 use semver::VersionReq;
-use std::{collections::{BTreeMap, HashMap}, ops::Deref};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Deref,
+};
 
 use crate::krate_version::KrateVersion;
 
+/// Whether [`Krate::ask_version`]-style lookups should prefer the newest or
+/// the oldest version matching a requirement. Mirrors cargo's
+/// `-Z minimal-versions`, which resolves every requirement to its lowest
+/// satisfying version to check that declared lower bounds are real.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionSelectionMode {
+    Maximal,
+    Minimal,
+}
+
+/// The semver-compatible "epoch" a version belongs to, the way cargo
+/// deduplicates semver-compatible dependencies: the major number once it's
+/// `>= 1`, otherwise `0.minor`. So `1.4.0` and `2.0.0` are distinct epochs,
+/// and so are `0.2.x` and `0.3.x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Epoch {
+    Major(u64),
+    ZeroMinor(u64),
+}
+
+impl Epoch {
+    pub fn of(version: &semver::Version) -> Self {
+        if version.major >= 1 {
+            Epoch::Major(version.major)
+        } else {
+            Epoch::ZeroMinor(version.minor)
+        }
+    }
+}
+
 pub struct Krate {
     krate: Crate,
-    versions: BTreeMap<String, KrateVersion>,
-    asked_versions: HashMap<VersionReq, String>,
+    /// Keyed by [`semver::Version`] (not the raw version string) so
+    /// `.iter()`/`.iter().rev()` walk versions in actual semver order
+    /// instead of `BTreeMap<String, _>`'s lexicographic order, which sorts
+    /// e.g. `"0.9.0"` after `"0.10.0"`.
+    versions: BTreeMap<semver::Version, KrateVersion>,
+    asked_versions: HashMap<VersionReq, semver::Version>,
+    asked_min_versions: HashMap<VersionReq, semver::Version>,
+    msrv_asked_versions: HashMap<(VersionReq, RustVersion), semver::Version>,
+    /// Whether yanked versions are selectable at all. Off by default,
+    /// matching cargo's registry behavior where yanked releases are only
+    /// usable if already pinned in a lockfile.
+    allow_yanked: bool,
+    /// Versions that must remain selectable even when yanked, because
+    /// they're already pinned (e.g. in a lockfile being reproduced).
+    pinned: HashSet<semver::Version>,
 }
 
 impl From<Crate> for Krate {
@@ -15,15 +62,24 @@ impl From<Crate> for Krate {
         let versions = krate
             .versions()
             .into_iter()
-            .map(|version| (version.name().to_string(), version.clone().into()))
+            .map(|version| {
+                let version: KrateVersion = version.clone().into();
+                (version.semver().clone(), version)
+            })
             .collect();
 
         let asked_versions = HashMap::new();
+        let asked_min_versions = HashMap::new();
+        let msrv_asked_versions = HashMap::new();
 
         Self {
             krate,
             versions,
             asked_versions,
+            asked_min_versions,
+            msrv_asked_versions,
+            allow_yanked: false,
+            pinned: HashSet::new(),
         }
     }
 }
@@ -37,18 +93,326 @@ impl Deref for Krate {
 }
 
 impl Krate {
+    pub fn versions(&self) -> impl Iterator<Item = &KrateVersion> {
+        self.versions.values()
+    }
+
+    /// Like [`Self::versions`], but filtered to versions currently
+    /// selectable under `allow_yanked`/pinning, the same gate every
+    /// `ask_*`/`resolve_unified` method applies. Lets other offline
+    /// consumers (e.g. [`crate::offline_source::OfflineSource`]) agree with
+    /// those on which versions are in play, instead of reimplementing their
+    /// own yanked filter.
+    pub fn selectable_versions(&self) -> impl Iterator<Item = &KrateVersion> {
+        self.versions
+            .iter()
+            .filter(|(key, version)| self.is_selectable(key, version))
+            .map(|(_, version)| version)
+    }
+
+    /// Allows yanked versions to be selected by `ask_*` methods. Off by
+    /// default.
+    pub fn set_allow_yanked(&mut self, allow_yanked: bool) {
+        self.allow_yanked = allow_yanked;
+    }
+
+    /// Marks a version as pinned, keeping it selectable even when yanked and
+    /// `allow_yanked` is `false`.
+    pub fn pin(&mut self, version: semver::Version) {
+        self.pinned.insert(version);
+    }
+
+    fn is_selectable(&self, key: &semver::Version, version: &KrateVersion) -> bool {
+        !version.is_yanked() || self.allow_yanked || self.pinned.contains(key)
+    }
+
     pub fn ask_version(&mut self, req: &VersionReq) -> Option<&KrateVersion> {
         if let Some(version) = self.asked_versions.get(req) {
             return self.versions.get(version);
         }
 
         for (key, version) in self.versions.iter().rev() {
-            if req.matches(version.semver()) {
-                self.asked_versions.insert(req.to_owned(), key.to_owned());
+            if req.matches(version.semver()) && self.is_selectable(key, version) {
+                self.asked_versions.insert(req.to_owned(), key.clone());
+                return Some(version);
+            }
+        }
+
+        None
+    }
+
+    /// The lowest version satisfying `req`, mirroring [`Self::ask_version`]
+    /// but iterating candidates in ascending order.
+    pub fn ask_min_version(&mut self, req: &VersionReq) -> Option<&KrateVersion> {
+        if let Some(version) = self.asked_min_versions.get(req) {
+            return self.versions.get(version);
+        }
+
+        for (key, version) in self.versions.iter() {
+            if req.matches(version.semver()) && self.is_selectable(key, version) {
+                self.asked_min_versions.insert(req.to_owned(), key.clone());
                 return Some(version);
             }
         }
 
         None
     }
+
+    /// Dispatches to [`Self::ask_version`] or [`Self::ask_min_version`]
+    /// depending on `mode`, so a whole resolution run can pick maximal or
+    /// minimal selection consistently without branching at every call site.
+    pub fn ask_version_with_mode(
+        &mut self,
+        req: &VersionReq,
+        mode: VersionSelectionMode,
+    ) -> Option<&KrateVersion> {
+        match mode {
+            VersionSelectionMode::Maximal => self.ask_version(req),
+            VersionSelectionMode::Minimal => self.ask_min_version(req),
+        }
+    }
+
+    /// Like [`Self::ask_version`], but implements cargo's v3-resolver
+    /// rust-version gating: candidates whose declared `rust-version` exceeds
+    /// `toolchain` are skipped, so callers get the newest version actually
+    /// buildable on that toolchain. Versions with no declared `rust-version`
+    /// are always considered compatible.
+    pub fn ask_version_for_rust(
+        &mut self,
+        req: &VersionReq,
+        toolchain: &RustVersion,
+    ) -> Option<&KrateVersion> {
+        let msrv_key = (req.to_owned(), toolchain.clone());
+        if let Some(version) = self.msrv_asked_versions.get(&msrv_key) {
+            return self.versions.get(version);
+        }
+
+        for (key, version) in self.versions.iter().rev() {
+            if !req.matches(version.semver()) || !self.is_selectable(key, version) {
+                continue;
+            }
+
+            let compatible = match version.rust_version() {
+                Some(rust_version) => match rust_version.parse::<RustVersion>() {
+                    Ok(rust_version) => &rust_version <= toolchain,
+                    Err(_) => true,
+                },
+                None => true,
+            };
+
+            if compatible {
+                self.msrv_asked_versions.insert(msrv_key, key.clone());
+                return self.versions.get(key);
+            }
+        }
+
+        None
+    }
+
+    /// Unifies `reqs` the way cargo deduplicates semver-compatible
+    /// dependencies: buckets each requirement by the [`Epoch`]s it can
+    /// match, then for every epoch picks the single highest version
+    /// satisfying *all* requirements that landed in it. Epochs whose
+    /// requirements have no common match are omitted, giving callers the
+    /// minimal set of distinct builds of this crate needed across a
+    /// dependency set.
+    pub fn resolve_unified(&mut self, reqs: &[VersionReq]) -> BTreeMap<Epoch, &KrateVersion> {
+        let mut reqs_by_epoch = BTreeMap::<Epoch, Vec<&VersionReq>>::new();
+        for req in reqs {
+            for (key, version) in self.versions.iter() {
+                if !req.matches(version.semver()) || !self.is_selectable(key, version) {
+                    continue;
+                }
+
+                let bucket = reqs_by_epoch.entry(Epoch::of(version.semver())).or_default();
+                if !bucket.iter().any(|bucketed| **bucketed == *req) {
+                    bucket.push(req);
+                }
+            }
+        }
+
+        reqs_by_epoch
+            .into_iter()
+            .filter_map(|(epoch, reqs)| {
+                let (_, version) = self.versions.iter().rev().find(|(key, version)| {
+                    Epoch::of(version.semver()) == epoch
+                        && self.is_selectable(key, version)
+                        && reqs.iter().all(|req| req.matches(version.semver()))
+                })?;
+                Some((epoch, version))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a real `crates_index::Version` by deserializing the same
+    /// index JSON shape [`crate::index::parse_index`] reads, so these tests
+    /// exercise the production types rather than a stand-in.
+    fn version(vers: &str, yanked: bool, rust_version: Option<&str>) -> Version {
+        serde_json::from_value(serde_json::json!({
+            "name": "subject",
+            "vers": vers,
+            "deps": [],
+            "cksum": "0".repeat(64),
+            "features": {},
+            "yanked": yanked,
+            "rust_version": rust_version,
+        }))
+        .expect("valid index version fixture")
+    }
+
+    fn krate(versions: impl IntoIterator<Item = Version>) -> Krate {
+        Krate::from(Crate::new(versions.into_iter().collect()))
+    }
+
+    #[test]
+    fn epoch_groups_major_versions_and_zero_minor_versions_separately() {
+        assert_eq!(
+            Epoch::of(&semver::Version::parse("1.4.0").unwrap()),
+            Epoch::of(&semver::Version::parse("1.9.0").unwrap())
+        );
+        assert_ne!(
+            Epoch::of(&semver::Version::parse("1.4.0").unwrap()),
+            Epoch::of(&semver::Version::parse("2.0.0").unwrap())
+        );
+        assert_ne!(
+            Epoch::of(&semver::Version::parse("0.2.0").unwrap()),
+            Epoch::of(&semver::Version::parse("0.3.0").unwrap())
+        );
+    }
+
+    #[test]
+    fn ask_version_prefers_the_newest_match() {
+        let mut krate = krate([
+            version("1.0.0", false, None),
+            version("1.2.0", false, None),
+            version("2.0.0", false, None),
+        ]);
+
+        let req = VersionReq::parse("^1").unwrap();
+        let found = krate.ask_version(&req).unwrap();
+        assert_eq!(found.semver(), &semver::Version::parse("1.2.0").unwrap());
+    }
+
+    #[test]
+    fn ask_version_orders_by_semver_not_lexicographically() {
+        let mut krate = krate([
+            version("1.9.0", false, None),
+            version("1.10.0", false, None),
+        ]);
+
+        let req = VersionReq::parse("^1").unwrap();
+        let found = krate.ask_version(&req).unwrap();
+        assert_eq!(found.semver(), &semver::Version::parse("1.10.0").unwrap());
+    }
+
+    #[test]
+    fn ask_min_version_prefers_the_oldest_match() {
+        let mut krate = krate([
+            version("1.0.0", false, None),
+            version("1.2.0", false, None),
+            version("2.0.0", false, None),
+        ]);
+
+        let req = VersionReq::parse("^1").unwrap();
+        let found = krate.ask_min_version(&req).unwrap();
+        assert_eq!(found.semver(), &semver::Version::parse("1.0.0").unwrap());
+    }
+
+    #[test]
+    fn yanked_versions_are_excluded_by_default() {
+        let mut krate = krate([version("1.0.0", false, None), version("1.2.0", true, None)]);
+
+        let req = VersionReq::parse("^1").unwrap();
+        let found = krate.ask_version(&req).unwrap();
+        assert_eq!(found.semver(), &semver::Version::parse("1.0.0").unwrap());
+    }
+
+    #[test]
+    fn allow_yanked_makes_yanked_versions_selectable() {
+        let mut krate = krate([version("1.0.0", false, None), version("1.2.0", true, None)]);
+        krate.set_allow_yanked(true);
+
+        let req = VersionReq::parse("^1").unwrap();
+        let found = krate.ask_version(&req).unwrap();
+        assert_eq!(found.semver(), &semver::Version::parse("1.2.0").unwrap());
+    }
+
+    #[test]
+    fn pinning_a_yanked_version_keeps_it_selectable() {
+        let mut krate = krate([version("1.0.0", false, None), version("1.2.0", true, None)]);
+        krate.pin(semver::Version::parse("1.2.0").unwrap());
+
+        let req = VersionReq::parse("^1").unwrap();
+        let found = krate.ask_version(&req).unwrap();
+        assert_eq!(found.semver(), &semver::Version::parse("1.2.0").unwrap());
+    }
+
+    #[test]
+    fn ask_version_for_rust_skips_versions_requiring_a_newer_toolchain() {
+        let mut krate = krate([
+            version("1.0.0", false, Some("1.50")),
+            version("1.2.0", false, Some("1.80")),
+        ]);
+
+        let req = VersionReq::parse("^1").unwrap();
+        let toolchain: RustVersion = "1.60".parse().unwrap();
+        let found = krate.ask_version_for_rust(&req, &toolchain).unwrap();
+        assert_eq!(found.semver(), &semver::Version::parse("1.0.0").unwrap());
+    }
+
+    #[test]
+    fn resolve_unified_picks_the_highest_version_per_epoch() {
+        let mut krate = krate([
+            version("1.0.0", false, None),
+            version("1.4.0", false, None),
+            version("2.0.0", false, None),
+            version("2.3.0", false, None),
+        ]);
+
+        let reqs = [
+            VersionReq::parse(">=1.0.0, <2.0.0").unwrap(),
+            VersionReq::parse(">=2.0.0").unwrap(),
+        ];
+        let unified = krate.resolve_unified(&reqs);
+
+        assert_eq!(
+            unified.get(&Epoch::Major(1)).map(|v| v.semver()),
+            Some(&semver::Version::parse("1.4.0").unwrap())
+        );
+        assert_eq!(
+            unified.get(&Epoch::Major(2)).map(|v| v.semver()),
+            Some(&semver::Version::parse("2.3.0").unwrap())
+        );
+    }
+
+    #[test]
+    fn resolve_unified_excludes_yanked_versions() {
+        let mut krate = krate([version("1.0.0", false, None), version("1.4.0", true, None)]);
+
+        let reqs = [VersionReq::parse("^1").unwrap()];
+        let unified = krate.resolve_unified(&reqs);
+
+        assert_eq!(
+            unified.get(&Epoch::Major(1)).map(|v| v.semver()),
+            Some(&semver::Version::parse("1.0.0").unwrap())
+        );
+    }
+
+    #[test]
+    fn resolve_unified_omits_epochs_with_no_common_match() {
+        let mut krate = krate([version("1.0.0", false, None), version("1.9.0", false, None)]);
+
+        let reqs = [
+            VersionReq::parse(">=1.0.0, <1.5.0").unwrap(),
+            VersionReq::parse(">=1.5.0").unwrap(),
+        ];
+        let unified = krate.resolve_unified(&reqs);
+
+        assert!(unified.get(&Epoch::Major(1)).is_none());
+    }
 }
@@ -2,20 +2,22 @@ use anyhow::Context;
 use cargo::{
     GlobalContext,
     core::{
-        Shell,
+        Shell, Workspace,
         registry::PackageRegistry,
         resolver::{CliFeatures, HasDevUnits, ResolveBehavior},
     },
     sources::SourceConfigMap,
     util::{ConfigValue, context::Definition},
 };
+use cargo_util_schemas::manifest::RustVersion;
 use parking_lot::Mutex;
 use progress::Progress;
 use rayon::iter::{ParallelBridge, ParallelIterator};
+use semver::VersionReq;
 use serde::{Deserialize, Serialize};
 use std::{
     cell::LazyCell,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     fs::File,
     io::BufWriter,
@@ -27,10 +29,22 @@ use std::{
     },
 };
 
-use crate::synth_workspace::synth_workspace;
+use crate::{
+    checkpoint::Checkpoint,
+    krate::{Krate, VersionSelectionMode},
+    krate_version::KrateVersion,
+    resolve_offline::resolve_offline,
+    synth_workspace::synth_workspace,
+};
 
+mod checkpoint;
+mod feature_resolution;
 mod index;
+mod krate;
+mod krate_version;
+mod offline_source;
 mod progress;
+mod resolve_offline;
 mod synth_workspace;
 
 const SEARCH_CRATE: &str = "nu-ansi-term";
@@ -44,6 +58,12 @@ static INDEX_PATH: LazyPath = LazyPath::new(|| CWD.join("index"));
 static LOCK_FILES_PATH: LazyPath = LazyPath::new(|| CWD.join("lock-files"));
 static DEPENDENTS_PATH: LazyPath = LazyPath::new(|| CWD.join("dependents.json"));
 static UNRESOLVABLE_PATH: LazyPath = LazyPath::new(|| CWD.join("unresolvable.json"));
+static REV_DEPS_PATH: LazyPath = LazyPath::new(|| CWD.join("rev-deps.json"));
+static CHECKPOINT_PATH: LazyPath = LazyPath::new(|| CWD.join("checkpoint.ndjson"));
+static ADOPTION_PATH: LazyPath = LazyPath::new(|| CWD.join("adoption.json"));
+static VERSION_RECOMMENDATIONS_PATH: LazyPath =
+    LazyPath::new(|| CWD.join("nu-ansi-term-versions.json"));
+static OFFLINE_MISMATCHES_PATH: LazyPath = LazyPath::new(|| CWD.join("offline-mismatches.json"));
 
 static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(1);
 thread_local! {
@@ -96,11 +116,26 @@ thread_local! {
 }
 
 fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let refresh = args.iter().any(|arg| arg == "--refresh");
+    let offline = args.iter().any(|arg| arg == "--offline");
+    let all_versions = args.iter().any(|arg| arg == "--all-versions");
+    let rev_deps = args.iter().any(|arg| arg == "--rev-deps");
+    let allow_yanked = args.iter().any(|arg| arg == "--allow-yanked");
+    let minimal_versions = args.iter().any(|arg| arg == "--minimal-versions");
+    let offline_verify = args.iter().any(|arg| arg == "--offline-verify");
+
     let mut progress = Progress::new();
 
-    progress.spinner("Cloning", "crates.io registry");
-    index::ensure_index()?;
-    progress.finish("Cloned", "crates.io registry");
+    if offline {
+        progress.finish_warning("skipping crates.io registry refresh (--offline)");
+    } else {
+        index::ensure_index(&mut progress, refresh)?;
+        progress.finish(
+            if refresh { "Refreshed" } else { "Cloned" },
+            "crates.io registry",
+        );
+    }
 
     progress.spinner("Counting", "total number of crates");
     let total_crate_count = index::count_index()?;
@@ -110,9 +145,37 @@ fn main() -> anyhow::Result<()> {
     let index = index::parse_index(step)?;
     progress.finish("Parsed", "crates registry");
 
+    if rev_deps {
+        progress.spinner("Building", "reverse-dependency graph");
+        let transitive_targets = HashSet::from([SEARCH_CRATE]);
+        let rev_dep_graph = index::build_rev_dep_graph(&index, &transitive_targets);
+        progress.finish(
+            "Built",
+            format!("reverse-dependency graph for {} crates", rev_dep_graph.len()),
+        );
+
+        progress.spinner("Writing", "reverse-dependency graph");
+        let file = File::create(REV_DEPS_PATH.as_path())?;
+        let writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, &rev_dep_graph)?;
+        progress.finish("Writing", "reverse-dependency graph");
+    } else {
+        progress.finish_warning("skipping reverse-dependency graph (pass --rev-deps)");
+    }
+
+    progress.spinner("Loading", "resolve checkpoint");
+    let (checkpoint, checkpoint_state) = Checkpoint::open(CHECKPOINT_PATH.as_path())?;
+    progress.finish(
+        "Loaded",
+        format!(
+            "checkpoint with {} already-resolved crates",
+            checkpoint_state.processed.len()
+        ),
+    );
+
     let (step, warn) = progress.bar(index.len(), "Resolving", "crate dependencies");
-    let resolve_errors = Mutex::<Vec<ResolveError>>::default();
-    let dependents = Mutex::<Vec<(&str, &semver::Version)>>::default();
+    let resolve_errors = Mutex::new(checkpoint_state.resolve_errors);
+    let dependents = Mutex::new(checkpoint_state.dependents);
     index
         .iter()
         .flat_map(|(crate_name, versions)| {
@@ -128,6 +191,13 @@ fn main() -> anyhow::Result<()> {
                 }
             }
         })
+        .filter(|(_, _, version)| {
+            if checkpoint_state.processed.contains(&checkpoint_key(version)) {
+                step();
+                return false;
+            }
+            true
+        })
         .par_bridge()
         .try_for_each(|(crate_name, semver, version)| {
             step();
@@ -162,8 +232,9 @@ fn main() -> anyhow::Result<()> {
                             let err =
                                 ResolveError::from_str(crate_name.clone(), semver.clone(), err)
                                     .map_err(|err| anyhow::Error::msg(err))?;
-                            let mut resolve_errors = resolve_errors.lock();
-                            resolve_errors.push(err);
+                            checkpoint.record_resolve_error(&err)?;
+                            resolve_errors.lock().push(err);
+                            checkpoint.record_processed(&checkpoint_key(version))?;
                             return Ok(());
                         }
                     };
@@ -173,9 +244,27 @@ fn main() -> anyhow::Result<()> {
                         .find(|package_id| package_id.name().as_str() == SEARCH_CRATE)
                         .is_some()
                     {
-                        dependents.lock().push((crate_name, semver));
+                        match classify_dependent(
+                            crate_name,
+                            semver,
+                            version,
+                            &mut registry,
+                            &workspace,
+                        )? {
+                            Ok(kind) => {
+                                checkpoint.record_dependent(crate_name, semver, &kind)?;
+                                dependents
+                                    .lock()
+                                    .push((crate_name.to_string(), semver.clone(), kind));
+                            }
+                            Err(err) => {
+                                checkpoint.record_resolve_error(&err)?;
+                                resolve_errors.lock().push(err);
+                            }
+                        }
                     }
 
+                    checkpoint.record_processed(&checkpoint_key(version))?;
                     anyhow::Result::<_>::Ok(())
                 })
                 .with_context(|| format!("error while resolving {}@{}", crate_name, semver))?;
@@ -204,9 +293,388 @@ fn main() -> anyhow::Result<()> {
     serde_json::to_writer_pretty(writer, resolve_errors.lock().deref())?;
     progress.finish("Writing", "unresolvable crates");
 
+    let dependents_snapshot = dependents.lock().clone();
+
+    progress.spinner("Resolving", "nu-ansi-term version recommendations");
+    let mode = if minimal_versions {
+        VersionSelectionMode::Minimal
+    } else {
+        VersionSelectionMode::Maximal
+    };
+    let (recommendations, unified_epochs) =
+        recommend_nu_ansi_term_versions(&index, &dependents_snapshot, allow_yanked, mode)?;
+    progress.finish(
+        "Resolved",
+        format!(
+            "{} version recommendations across {unified_epochs} unified requirement epochs",
+            recommendations.len()
+        ),
+    );
+
+    progress.spinner("Writing", "nu-ansi-term version recommendations");
+    let file = File::create(VERSION_RECOMMENDATIONS_PATH.as_path())?;
+    let writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(writer, &recommendations)?;
+    progress.finish("Writing", "nu-ansi-term version recommendations");
+
+    if offline_verify {
+        progress.spinner("Building", "offline krate index");
+        let mut krates: HashMap<String, Krate> = index
+            .iter()
+            .map(|(name, versions)| {
+                let krate = crates_index::Crate::new(versions.values().cloned().collect());
+                (name.clone(), Krate::from(krate))
+            })
+            .collect();
+        progress.finish("Built", format!("offline krate index for {} crates", krates.len()));
+
+        let (step, _) = progress.bar(
+            dependents_snapshot.len(),
+            "Verifying",
+            "dependents against the offline resolver",
+        );
+        let mismatches = GLOBAL_CONTEXT.with(|gctx| -> anyhow::Result<Vec<_>> {
+            let gctx = gctx.as_ref().map_err(|err| anyhow::anyhow!("{err}"))?;
+            let mut mismatches = Vec::new();
+
+            for (crate_name, semver_version, kind) in &dependents_snapshot {
+                step();
+                let Some(version) = index
+                    .get(crate_name)
+                    .and_then(|versions| versions.get(semver_version))
+                else {
+                    continue;
+                };
+
+                let present_offline = resolve_offline(crate_name, version, &mut krates, gctx)
+                    .map(|resolve| {
+                        resolve
+                            .iter()
+                            .any(|package_id| package_id.name().as_str() == SEARCH_CRATE)
+                    })
+                    .unwrap_or(false);
+
+                if !present_offline {
+                    mismatches.push((crate_name.clone(), semver_version.clone(), kind.clone()));
+                }
+            }
+
+            Ok(mismatches)
+        })?;
+        drop((step,));
+        progress.finish(
+            "Verified",
+            format!(
+                "{} offline-resolve mismatches out of {} dependents",
+                mismatches.len(),
+                dependents_snapshot.len()
+            ),
+        );
+
+        progress.spinner("Writing", "offline-resolve mismatches");
+        let file = File::create(OFFLINE_MISMATCHES_PATH.as_path())?;
+        let writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, &mismatches)?;
+        progress.finish("Writing", "offline-resolve mismatches");
+    }
+
+    if all_versions {
+        progress.spinner("Resolving", "nu-ansi-term adoption across all published versions");
+        let adoption = resolve_adoption_history(&index)?;
+        progress.finish(
+            "Resolved",
+            format!("adoption history for {} dependents", adoption.len()),
+        );
+
+        progress.spinner("Writing", "adoption history");
+        let file = File::create(ADOPTION_PATH.as_path())?;
+        let writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, &adoption)?;
+        progress.finish("Writing", "adoption history");
+    }
+
     Ok(())
 }
 
+/// A version truncated to its `major.minor.patch` triple, used to keep the
+/// adoption interval compact instead of storing every version string a
+/// crate was ever resolved at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct MiniVer {
+    major: u64,
+    minor: u64,
+    patch: u64,
+}
+
+impl From<&semver::Version> for MiniVer {
+    fn from(version: &semver::Version) -> Self {
+        MiniVer {
+            major: version.major,
+            minor: version.minor,
+            patch: version.patch,
+        }
+    }
+}
+
+/// The first and last version of a crate (by semver order, i.e. `MiniVer`'s
+/// derived `Ord`) in which [`SEARCH_CRATE`] was seen in the resolved
+/// dependency graph. The crates.io index has no publish-timestamp field, so
+/// for a crate that released versions out of semver order, this is *not*
+/// necessarily the actual chronological first/last adoption.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VersionInterval {
+    first_seen: MiniVer,
+    last_seen: MiniVer,
+}
+
+/// Resolves every published, non-yanked version of every crate (rather than
+/// just the latest) to record when each dependent first and last depended on
+/// [`SEARCH_CRATE`], answering "which crates ever depended on it" and "when
+/// did they adopt or drop it" instead of reflecting a single point in time.
+fn resolve_adoption_history(
+    index: &HashMap<String, BTreeMap<semver::Version, crates_index::Version>>,
+) -> anyhow::Result<BTreeMap<String, VersionInterval>> {
+    let adoption = Mutex::<HashMap<String, VersionInterval>>::default();
+
+    index
+        .iter()
+        .flat_map(|(crate_name, versions)| {
+            versions
+                .iter()
+                .filter(|(_, version)| !version.is_yanked())
+                .map(move |(semver, version)| (crate_name, semver, version))
+        })
+        .par_bridge()
+        .try_for_each(|(crate_name, semver, version)| {
+            GLOBAL_CONTEXT
+                .with(|gctx| {
+                    let gctx = gctx.as_ref().map_err(|err| anyhow::anyhow!("{err}"))?;
+
+                    let workspace = synth_workspace(crate_name, version, &gctx)?;
+                    let mut registry = PackageRegistry::new_with_source_config(
+                        &gctx,
+                        SourceConfigMap::new(&gctx)?,
+                    )?;
+                    registry.lock_patches();
+                    let resolve = cargo::ops::resolve_with_previous(
+                        &mut registry,
+                        &workspace,
+                        &CliFeatures {
+                            features: Default::default(),
+                            all_features: true,
+                            uses_default_features: true,
+                        },
+                        HasDevUnits::No,
+                        None,
+                        None,
+                        &[],
+                        false,
+                    );
+
+                    let Ok(resolve) = resolve else {
+                        return anyhow::Result::<_>::Ok(());
+                    };
+
+                    if resolve
+                        .iter()
+                        .any(|package_id| package_id.name().as_str() == SEARCH_CRATE)
+                    {
+                        let mini_ver = MiniVer::from(semver);
+                        adoption
+                            .lock()
+                            .entry(crate_name.clone())
+                            .and_modify(|interval| {
+                                interval.first_seen = interval.first_seen.min(mini_ver);
+                                interval.last_seen = interval.last_seen.max(mini_ver);
+                            })
+                            .or_insert(VersionInterval {
+                                first_seen: mini_ver,
+                                last_seen: mini_ver,
+                            });
+                    }
+
+                    anyhow::Result::<_>::Ok(())
+                })
+                .with_context(|| format!("error while resolving {}@{}", crate_name, semver))
+        })?;
+
+    Ok(adoption.into_inner().into_iter().collect())
+}
+
+/// Stable dedup key for a resolved crate version, reusing the same
+/// `name@version` shape as [`KrateVersion::as_key`].
+fn checkpoint_key(version: &crates_index::Version) -> String {
+    let krate_version: KrateVersion = version.clone().into();
+    let (name, version) = krate_version.as_key();
+    format!("{name}@{version}")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum DependentKind {
+    Required,
+    DefaultFeature,
+    OptionalOnly,
+}
+
+/// Classifies how strongly `version` depends on [`SEARCH_CRATE`]: whether it
+/// is pulled in unconditionally, only via the crate's default features, or
+/// only via a feature that isn't on by default.
+///
+/// A default-features resolve can fail for reasons unrelated to
+/// [`SEARCH_CRATE`] (e.g. an unrelated yanked dependency) even though the
+/// caller's all-features resolve just succeeded. That failure is recorded as
+/// a [`ResolveError`] and returned as the `Err` side rather than propagated,
+/// so one unresolvable crate doesn't abort the whole resolve loop. A failure
+/// whose message matches no [`ResolveErrorKind`] is still propagated.
+fn classify_dependent(
+    crate_name: &str,
+    semver: &semver::Version,
+    version: &crates_index::Version,
+    registry: &mut PackageRegistry,
+    workspace: &Workspace,
+) -> anyhow::Result<Result<DependentKind, ResolveError>> {
+    let default_resolve = cargo::ops::resolve_with_previous(
+        registry,
+        workspace,
+        &CliFeatures {
+            features: Default::default(),
+            all_features: false,
+            uses_default_features: true,
+        },
+        HasDevUnits::No,
+        None,
+        None,
+        &[],
+        false,
+    );
+
+    let default_resolve = match default_resolve {
+        Ok(resolve) => resolve,
+        Err(err) => {
+            let err = ResolveError::from_str(crate_name.to_string(), semver.clone(), err)
+                .map_err(anyhow::Error::msg)?;
+            return Ok(Err(err));
+        }
+    };
+
+    let present_with_default_features = default_resolve
+        .iter()
+        .any(|package_id| package_id.name().as_str() == SEARCH_CRATE);
+
+    let direct_dependency = version
+        .dependencies()
+        .iter()
+        .find(|dependency| dependency.crate_name() == SEARCH_CRATE);
+
+    Ok(Ok(match (present_with_default_features, direct_dependency) {
+        (true, Some(dependency)) if !dependency.is_optional() => DependentKind::Required,
+        (true, _) => DependentKind::DefaultFeature,
+        (false, _) => DependentKind::OptionalOnly,
+    }))
+}
+
+/// What [`recommend_nu_ansi_term_versions`] worked out a single dependent
+/// would resolve [`SEARCH_CRATE`] to, offline and without running cargo's
+/// resolver at all: just [`Krate`]'s own version-selection rules applied to
+/// the dependent's declared requirement.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VersionRecommendation {
+    dependent: String,
+    dependent_version: semver::Version,
+    requirement: String,
+    selected: Option<semver::Version>,
+    for_rust_version: Option<semver::Version>,
+}
+
+/// For every recorded dependent, asks an offline, index-backed [`Krate`]
+/// (built once up front, with no cargo resolver involved) which
+/// [`SEARCH_CRATE`] version it would pick for that dependent's declared
+/// requirement, in both `mode`'s selection order and MSRV-gated by the
+/// dependent's own `rust-version`. An optional dependency edge is only
+/// considered if [`feature_resolution::resolve_features`] shows the
+/// dependent's own default features actually activate it, the same
+/// distinction [`DependentKind::OptionalOnly`] draws. Also unifies every
+/// collected requirement across epochs the way `cargo`'s resolver would,
+/// returning how many distinct epochs survived unification alongside the
+/// per-dependent recommendations.
+fn recommend_nu_ansi_term_versions(
+    index: &HashMap<String, BTreeMap<semver::Version, crates_index::Version>>,
+    dependents: &[(String, semver::Version, DependentKind)],
+    allow_yanked: bool,
+    mode: VersionSelectionMode,
+) -> anyhow::Result<(Vec<VersionRecommendation>, usize)> {
+    let Some(nu_ansi_term_versions) = index.get(SEARCH_CRATE) else {
+        return Ok((Vec::new(), 0));
+    };
+
+    let mut krate = Krate::from(crates_index::Crate::new(
+        nu_ansi_term_versions.values().cloned().collect(),
+    ));
+    krate.set_allow_yanked(allow_yanked);
+    if let Some((semver, _)) = nu_ansi_term_versions.iter().next_back() {
+        krate.pin(semver.clone());
+    }
+
+    let mut reqs = Vec::new();
+    let mut recommendations = Vec::new();
+
+    for (crate_name, semver_version, _) in dependents {
+        let Some(dependent) = index
+            .get(crate_name)
+            .and_then(|versions| versions.get(semver_version))
+        else {
+            continue;
+        };
+
+        let Some(dependency) = dependent
+            .dependencies()
+            .iter()
+            .find(|dependency| dependency.crate_name() == SEARCH_CRATE)
+        else {
+            continue;
+        };
+
+        // A declared dependency edge isn't necessarily an active one: if
+        // it's optional, it only counts when the dependent's own
+        // default-feature selection actually turns it on.
+        if dependency.is_optional() {
+            let Ok(activated) = feature_resolution::resolve_features(dependent, &[], true) else {
+                continue;
+            };
+            if !activated.optional_dependencies.contains(dependency.name()) {
+                continue;
+            }
+        }
+
+        let Ok(req) = VersionReq::parse(dependency.requirement()) else {
+            continue;
+        };
+
+        let selected = krate
+            .ask_version_with_mode(&req, mode)
+            .map(|version| version.semver().clone());
+
+        let for_rust_version = dependent
+            .rust_version()
+            .and_then(|rust_version| rust_version.parse::<RustVersion>().ok())
+            .and_then(|toolchain| krate.ask_version_for_rust(&req, &toolchain))
+            .map(|version| version.semver().clone());
+
+        recommendations.push(VersionRecommendation {
+            dependent: crate_name.clone(),
+            dependent_version: semver_version.clone(),
+            requirement: dependency.requirement().to_string(),
+            selected,
+            for_rust_version,
+        });
+        reqs.push(req);
+    }
+
+    let unified_epochs = krate.resolve_unified(&reqs).len();
+
+    Ok((recommendations, unified_epochs))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ResolveError {
     crate_name: String,
@@ -0,0 +1,97 @@
+use std::{collections::HashMap, task::Poll, time::SystemTime};
+
+use cargo::{
+    GlobalContext,
+    core::{Dependency, Package, PackageId, SourceId, source::MaybePackage},
+    sources::{IndexSummary, source::QueryKind},
+    util::errors::CargoResult,
+};
+
+use crate::{krate::Krate, synth_workspace::synth_summary};
+
+/// A `cargo::core::Source` that answers queries entirely from the
+/// `crates_index` data already parsed into `Krate`s, so the resolver can be
+/// driven without ever touching the network or a real registry checkout.
+/// Candidate summaries are synthesized per-query via [`synth_summary`], the
+/// same helper [`crate::synth_workspace`] uses for the root package.
+pub struct OfflineSource<'gctx> {
+    source_id: SourceId,
+    gctx: &'gctx GlobalContext,
+    krates: &'gctx mut HashMap<String, Krate>,
+}
+
+impl<'gctx> OfflineSource<'gctx> {
+    pub fn new(
+        source_id: SourceId,
+        gctx: &'gctx GlobalContext,
+        krates: &'gctx mut HashMap<String, Krate>,
+    ) -> Self {
+        Self {
+            source_id,
+            gctx,
+            krates,
+        }
+    }
+}
+
+impl<'gctx> cargo::sources::source::Source for OfflineSource<'gctx> {
+    fn source_id(&self) -> SourceId {
+        self.source_id
+    }
+
+    fn supports_checksums(&self) -> bool {
+        false
+    }
+
+    fn requires_precise(&self) -> bool {
+        false
+    }
+
+    fn query(
+        &mut self,
+        dep: &Dependency,
+        _kind: QueryKind,
+        f: &mut dyn FnMut(IndexSummary),
+    ) -> Poll<CargoResult<()>> {
+        let Some(krate) = self.krates.get(dep.package_name().as_str()) else {
+            return Poll::Ready(Ok(()));
+        };
+
+        for version in krate.selectable_versions() {
+            if !dep.version_req().matches(version.semver()) {
+                continue;
+            }
+
+            let summary = synth_summary(dep.package_name().as_str(), version, self.gctx)?;
+            f(IndexSummary::Candidate(summary));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn invalidate_cache(&mut self) {}
+
+    fn set_quiet(&mut self, _quiet: bool) {}
+
+    fn download(&mut self, id: PackageId) -> CargoResult<MaybePackage> {
+        anyhow::bail!("offline source cannot download {id}, it only resolves version metadata")
+    }
+
+    fn finish_download(&mut self, id: PackageId, _data: Vec<u8>) -> CargoResult<Package> {
+        anyhow::bail!("offline source never starts a download for {id}")
+    }
+
+    fn fingerprint(&self, pkg: &Package) -> CargoResult<String> {
+        Ok(pkg.package_id().version().to_string())
+    }
+
+    fn describe(&self) -> String {
+        format!("offline index source ({})", self.source_id)
+    }
+
+    fn add_source_id(&mut self, _source: SourceId) {}
+
+    fn invalidated_prior_to(&self, _when: SystemTime) -> bool {
+        false
+    }
+}
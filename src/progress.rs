@@ -135,6 +135,7 @@ impl Progress {
             progress: self,
             prefix: prefix.into(),
             msg: msg.into(),
+            counter: Default::default(),
         }
     }
 }
@@ -143,6 +144,11 @@ pub struct GixProgress<'p> {
     progress: &'p mut Progress,
     prefix: Cow<'static, str>,
     msg: Cow<'static, str>,
+    /// Backing store for [`gix::Count::counter`], shared with whatever gix
+    /// hands it to (e.g. a worker thread driving a fetch or checkout), so
+    /// that thread's updates are reflected by `step`/`set` here too instead
+    /// of only ever tracking the progress bar's own position.
+    counter: gix::progress::StepShared,
 }
 
 impl<'p> gix::Progress for GixProgress<'p> {
@@ -189,25 +195,24 @@ impl<'p> gix::Progress for GixProgress<'p> {
 
 impl<'p> gix::Count for GixProgress<'p> {
     fn set(&self, step: gix::progress::prodash::progress::Step) {
+        self.counter.store(step, std::sync::atomic::Ordering::SeqCst);
         if let Some(pb) = &self.progress.progress_bar {
             pb.set_position(step as u64);
         }
     }
 
     fn step(&self) -> gix::progress::prodash::progress::Step {
-        match &self.progress.progress_bar {
-            Some(pb) => pb.position() as usize,
-            None => 0,
-        }
+        self.counter.load(std::sync::atomic::Ordering::SeqCst)
     }
 
     fn inc_by(&self, step: gix::progress::prodash::progress::Step) {
+        self.counter.fetch_add(step, std::sync::atomic::Ordering::SeqCst);
         if let Some(pb) = &self.progress.progress_bar {
             pb.inc(step as u64);
         }
     }
 
     fn counter(&self) -> gix::progress::StepShared {
-        unimplemented!("the internal position is not exposed")
+        self.counter.clone()
     }
 }
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use cargo::{
+    GlobalContext,
+    core::{
+        SourceId,
+        registry::PackageRegistry,
+        resolver::{CliFeatures, HasDevUnits, Resolve},
+    },
+    sources::SourceConfigMap,
+};
+
+use crate::{krate::Krate, offline_source::OfflineSource, synth_workspace::synth_workspace};
+
+/// Resolves `crate_name@version` entirely offline, backed by the
+/// `crates_index` data already held in `krates`. The result is a fully
+/// resolved graph equivalent to a `Cargo.lock`, computed without downloading
+/// any crate sources: the crates.io source is overlaid with an
+/// [`OfflineSource`] that answers every query from the in-memory index.
+pub fn resolve_offline(
+    crate_name: &str,
+    version: &crates_index::Version,
+    krates: &mut HashMap<String, Krate>,
+    gctx: &GlobalContext,
+) -> anyhow::Result<Resolve> {
+    let workspace = synth_workspace(crate_name, version, gctx)?;
+    let source_id = SourceId::crates_io(gctx)?;
+
+    let mut registry =
+        PackageRegistry::new_with_source_config(gctx, SourceConfigMap::new(gctx)?)?;
+    registry.add_overlay(source_id, Box::new(OfflineSource::new(source_id, gctx, krates)));
+    registry.lock_patches();
+
+    cargo::ops::resolve_with_previous(
+        &mut registry,
+        &workspace,
+        &CliFeatures {
+            features: Default::default(),
+            all_features: true,
+            uses_default_features: true,
+        },
+        HasDevUnits::No,
+        None,
+        None,
+        &[],
+        false,
+    )
+}
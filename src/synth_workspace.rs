@@ -81,7 +81,7 @@ fn synth_manifest<'gctx>(
     ))
 }
 
-fn synth_summary<'gctx>(
+pub(crate) fn synth_summary<'gctx>(
     crate_name: &str,
     version: &Version,
     gctx: &'gctx GlobalContext,